@@ -14,6 +14,11 @@ pub enum SMTError {
     Undefined,
     Unsat,
     AssertionError(String),
+    /// `assert` was given the wrong number of operands for the function being asserted.
+    ArityMismatch { expected: usize, found: usize },
+    /// An operand to `assert` did not have the sort the function expects at that position.
+    /// `position` is the zero-based index of the offending operand.
+    SortMismatch { expected: String, found: String, position: usize },
 }
 
 pub type SMTResult<T> = Result<T, SMTError>;
@@ -29,11 +34,8 @@ pub type SMTResult<T> = Result<T, SMTError>;
 ///  - declare_sort
 ///  - define_sort
 ///  - get_proof
-///  - get_unsat_core
 ///  - get_value
 ///  - get_assignment
-///  - push
-///  - pop
 ///  - get_option
 ///  - set_option
 ///  - get_info
@@ -51,7 +53,7 @@ pub trait SMTBackend {
         where T: AsRef<str>,
               P: Into<<<Self as SMTBackend>::Logic as Logic>::Sorts>;
 
-    fn assert<T: Into<<<Self as SMTBackend>::Logic as Logic>::Fns>>(&mut self, T, &[Self::Idx]) -> Self::Idx;
+    fn assert<T: Into<<<Self as SMTBackend>::Logic as Logic>::Fns>>(&mut self, T, &[Self::Idx]) -> SMTResult<Self::Idx>;
     // Adding a way to add a timeout to check_sat and solve methods.
     // If no value is provided it will default to indefinite wait.
     fn check_sat<S: SMTProc>(&mut self, &mut S, Option<u64>) -> SMTResult<bool>;
@@ -80,4 +82,19 @@ pub trait SMTNode: fmt::Display {
     fn is_bool(&self) -> bool {
         false
     }
+
+    /// The sort of this node's value: for a variable or constant, the sort of the value itself;
+    /// for a function, the sort of its result. Returns `None` when the concrete `Logic` doesn't
+    /// have enough static information to report one, in which case sort checking is skipped for
+    /// that node. A variable's sort is taken from its declaration instead, so this is not
+    /// consulted for variables.
+    fn sort(&self) -> Option<String> {
+        None
+    }
+
+    /// The sort each operand must have when this node is used as a function, in operand order.
+    /// `None` for variables and constants, and for functions whose arity isn't statically known.
+    fn signature(&self) -> Option<Vec<String>> {
+        None
+    }
 }
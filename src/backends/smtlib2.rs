@@ -3,12 +3,14 @@
 //! This backend outputs the constraints in standard smt-lib2 format. Hence,
 //! any solver that supports this format maybe used to solve for constraints.
 
-use std::process::Child;
-use std::collections::HashMap;
+use std::process::{Child, ChildStdout};
+use std::collections::{HashMap, HashSet};
 use std::io::{Read, Write};
 use regex::Regex;
 use std::time::Duration;
 use std::sync::mpsc;
+use std::thread;
+use num::{BigInt, ToPrimitive};
 
 use petgraph::graph::{Graph, NodeIndex};
 use petgraph::EdgeDirection;
@@ -16,6 +18,44 @@ use petgraph::visit::EdgeRef;
 
 use backends::backend::{Logic, SMTBackend, SMTError, SMTNode, SMTResult};
 
+/// A solver-reported value for a single model variable, tagged with enough type information to
+/// avoid the lossy `u64` truncation that a bare integer return would impose.
+///
+/// `BitVec` keeps every bit of the literal the solver returned (least-significant bit first) so
+/// wide bit-vectors are not folded into a `u64`, and `Int` uses an arbitrary-precision `BigInt` so
+/// negative and wide integers round-trip exactly.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ModelValue {
+    Bool(bool),
+    BitVec { bits: Vec<bool>, width: usize },
+    Int(BigInt),
+    Real(f64),
+    Bytes(String),
+}
+
+impl ModelValue {
+    /// Collapses the value back down to a `u64`, for callers that only need the old, lossy
+    /// representation. Non-numeric values (`Real`, `Bytes`) are reported as `0`.
+    pub fn to_u64(&self) -> u64 {
+        match *self {
+            ModelValue::Bool(b) => b as u64,
+            ModelValue::BitVec { ref bits, .. } => {
+                bits.iter().rev().fold(0u64, |acc, &b| (acc << 1) | (b as u64))
+            }
+            ModelValue::Int(ref i) => i.to_u64().unwrap_or(0),
+            ModelValue::Real(_) | ModelValue::Bytes(_) => 0,
+        }
+    }
+}
+
+// A `read` call that hit its timeout before the worker thread finished. Kept around so the next
+// `read` can reclaim the thread's result (and the `ChildStdout` it still owns) instead of
+// starting a fresh read and leaking the old one, which would otherwise close the pipe for good
+// the moment that thread eventually finishes.
+pub struct PendingRead {
+    recv: mpsc::Receiver<(ChildStdout, Result<String, SMTError>)>,
+}
+
 /// Trait that needs to be implemented in order to support a new solver. `SMTProc` is short for
 /// "SMT Process".
 ///
@@ -33,6 +73,8 @@ pub trait SMTProc {
     fn init(&mut self);
     /// Return a mutable reference to the process pipe.
     fn pipe<'a>(&'a mut self) -> &'a mut Child;
+    /// Mutable storage for a `read` that timed out, so a later call can pick its result back up.
+    fn pending_read<'a>(&'a mut self) -> &'a mut Option<PendingRead>;
 
     fn write<T: AsRef<str>>(&mut self, s: T) -> Result<(), String> {
         // TODO: Check for errors.
@@ -43,35 +85,105 @@ pub trait SMTProc {
         Ok(())
     }
 
-    fn read(&mut self, timeout: Option<u64>) -> Result<String, SMTError> {
-        // Important point to note here is that, if the data available to read
-        // is exactly 2048 bytes, then this reading mechanism fails and will end up waiting to
-        // read more data (when none is available) indefinitely.
-        let mut bytes_read = [0; 2048];
+    // Reads until parens balance (ignoring `|quoted|` and `"strings"`), or a bare `sat`/`unsat`/
+    // `unknown`/`success` line is seen.
+    fn read_sexpr(stdout: &mut ChildStdout) -> Result<String, SMTError> {
+        let mut buf = [0; 4096];
         let mut s = String::new();
-        let solver = self.pipe();
+        let mut depth: i64 = 0;
+        let mut opened = false;
+        let mut in_string = false;
+        let mut in_quoted_symbol = false;
+
+        loop {
+            let n = match stdout.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(_) => return Err(SMTError::Undefined),
+            };
+            let chunk = String::from_utf8_lossy(&buf[0..n]).into_owned();
+
+            for c in chunk.chars() {
+                match c {
+                    '"' if !in_quoted_symbol => in_string = !in_string,
+                    '|' if !in_string => in_quoted_symbol = !in_quoted_symbol,
+                    '(' if !in_string && !in_quoted_symbol => {
+                        depth += 1;
+                        opened = true;
+                    }
+                    ')' if !in_string && !in_quoted_symbol => depth -= 1,
+                    _ => {}
+                }
+            }
 
-        let (send, recv) = mpsc::channel::<bool>();
+            s = format!("{}{}", s, chunk);
 
-        if let Some(ref mut stdout) = solver.stdout.as_mut() {
-            let n = stdout.read(&mut bytes_read).unwrap();
-            s = format!("{}{}",
-                        s,
-                        String::from_utf8(bytes_read[0..n].to_vec()).unwrap());
-            // Sends a response on the channel, indicating that the output has been generated.
-             let _ = send.send(true);
+            if opened && depth <= 0 {
+                break;
+            }
+
+            if !opened {
+                match s.trim() {
+                    "sat" | "unsat" | "unknown" | "success" => break,
+                    _ => {}
+                }
+            }
         }
 
-        if timeout.is_some() {
-            let result = recv.recv_timeout(Duration::from_millis(timeout.unwrap()));
-            if result.is_ok() {
-                Ok(s)
-            } else {
-                Err(SMTError::Timeout)
+        Ok(s)
+    }
+
+    fn read(&mut self, timeout: Option<u64>) -> Result<String, SMTError> {
+        // A previous call may have timed out while its worker thread was still reading; check
+        // whether it has finished in the meantime before starting another one.
+        if let Some(pending) = self.pending_read().take() {
+            match pending.recv.try_recv() {
+                Ok((stdout, result)) => {
+                    self.pipe().stdout = Some(stdout);
+                    return result;
+                }
+                Err(mpsc::TryRecvError::Empty) => {
+                    *self.pending_read() = Some(pending);
+                    return Err(SMTError::Timeout);
+                }
+                // The worker thread died without sending anything and took `stdout` with it;
+                // there is nothing left to reclaim, so the code below finds no pipe to read
+                // from and returns `Undefined`.
+                Err(mpsc::TryRecvError::Disconnected) => {}
             }
+        }
+
+        let mut stdout = match self.pipe().stdout.take() {
+            Some(stdout) => stdout,
+            None => return Err(SMTError::Undefined),
+        };
+
+        // The accumulating read runs on a worker thread so a `recv_timeout` can bound how long
+        // we wait for it, rather than blocking the caller indefinitely on the pipe.
+        let (send, recv) = mpsc::channel();
+        thread::spawn(move || {
+            let result = Self::read_sexpr(&mut stdout);
+            let _ = send.send((stdout, result));
+        });
+
+        let received = if let Some(ms) = timeout {
+            recv.recv_timeout(Duration::from_millis(ms)).ok()
         } else {
-            let _ = recv.recv();
-            Ok(s)
+            recv.recv().ok()
+        };
+
+        match received {
+            Some((stdout, result)) => {
+                self.pipe().stdout = Some(stdout);
+                result
+            }
+            // Keep the receiver (and the worker thread's eventual ownership of `stdout`) around
+            // so the next `read` can reclaim them instead of the pipe closing for good once this
+            // thread finishes.
+            None => {
+                *self.pending_read() = Some(PendingRead { recv: recv });
+                Err(SMTError::Timeout)
+            }
         }
     }
 }
@@ -81,6 +193,15 @@ pub enum EdgeData {
     EdgeOrder(usize),
 }
 
+// Bookkeeping for a single `(push 1)` level: the variable declarations and assertion roots that
+// were introduced while the scope was active, so `pop` can forget them again and `generate_asserts`
+// knows not to re-emit them once the solver has already seen them.
+#[derive(Clone, Debug, Default)]
+struct Scope {
+    vars: Vec<String>,
+    asserts: Vec<NodeIndex>,
+}
+
 #[derive(Clone, Debug)]
 pub struct SMTLib2<T: Logic> {
     logic: Option<T>,
@@ -88,6 +209,18 @@ pub struct SMTLib2<T: Logic> {
     var_index: usize,
     var_map: HashMap<String, (NodeIndex, T::Sorts)>,
     idx_map: HashMap<NodeIndex, String>,
+    scopes: Vec<Scope>,
+    emitted_vars: HashSet<String>,
+    emitted_asserts: HashSet<NodeIndex>,
+    // Variables and asserts that belonged to a now-popped scope. Unlike `emitted_vars` /
+    // `emitted_asserts`, membership here is permanent: it keeps `generate_asserts` from treating
+    // a popped node as "not yet emitted" and silently re-asserting it under whatever scope
+    // happens to be current later on.
+    dead_vars: HashSet<String>,
+    dead_asserts: HashSet<NodeIndex>,
+    produce_unsat_cores: bool,
+    named_asserts: HashMap<String, NodeIndex>,
+    assert_labels: HashMap<NodeIndex, String>,
 }
 
 impl<L: Logic> SMTLib2<L> {
@@ -98,10 +231,119 @@ impl<L: Logic> SMTLib2<L> {
             var_index: 0,
             var_map: HashMap::new(),
             idx_map: HashMap::new(),
+            scopes: Vec::new(),
+            emitted_vars: HashSet::new(),
+            emitted_asserts: HashSet::new(),
+            dead_vars: HashSet::new(),
+            dead_asserts: HashSet::new(),
+            produce_unsat_cores: false,
+            named_asserts: HashMap::new(),
+            assert_labels: HashMap::new(),
         };
         solver
     }
 
+    /// Turns on `(get-unsat-core)` support by emitting `(set-option :produce-unsat-cores true)`.
+    /// Must be called before any asserts that should be nameable are sent to the solver, since
+    /// labels are only attached to asserts emitted while this is active.
+    pub fn set_produce_unsat_cores<S: SMTProc>(&mut self, smt_proc: &mut S, enable: bool) {
+        let _ = smt_proc.write(format!("(set-option :produce-unsat-cores {})\n", enable));
+        self.produce_unsat_cores = enable;
+    }
+
+    /// Like [`SMTBackend::assert`](trait.SMTBackend.html#tymethod.assert), but records the
+    /// assertion under `label` so it can be identified in a later
+    /// [`get_unsat_core`](#method.get_unsat_core).
+    pub fn assert_named<T: Into<L::Fns>>(&mut self, assert: T, ops: &[NodeIndex], label: &str) -> SMTResult<NodeIndex> {
+        let idx = SMTBackend::assert(self, assert, ops);
+        if let Ok(ni) = idx {
+            self.named_asserts.insert(label.to_owned(), ni);
+            self.assert_labels.insert(ni, label.to_owned());
+        }
+        idx
+    }
+
+    /// After an `unsat` result, fetches `(get-unsat-core)` and maps the labels the solver
+    /// reports back to the `NodeIndex` values they were asserted under via
+    /// [`assert_named`](#method.assert_named).
+    pub fn get_unsat_core<S: SMTProc>(&mut self, smt_proc: &mut S) -> SMTResult<Vec<NodeIndex>> {
+        let _ = smt_proc.write("(get-unsat-core)\n".to_owned());
+        let read_result = smt_proc.read(None);
+
+        if read_result.is_err() {
+            return Err(SMTError::Undefined);
+        }
+
+        let read_string = read_result.unwrap();
+        let mut core = Vec::new();
+        for label in read_string.trim().trim_matches(|c| c == '(' || c == ')').split_whitespace() {
+            if let Some(idx) = self.named_asserts.get(label) {
+                core.push(*idx);
+            }
+        }
+        Ok(core)
+    }
+
+    /// Opens a new assertion-stack scope by emitting any asserts generated so far followed by
+    /// `(push 1)`. Variables and asserts introduced after this call are tracked against the new
+    /// scope so a matching [`pop`](#method.pop) can forget them.
+    pub fn push<S: SMTProc>(&mut self, smt_proc: &mut S) {
+        let _ = smt_proc.write(self.generate_asserts());
+        let _ = smt_proc.write("(push 1)\n".to_owned());
+        self.scopes.push(Scope::default());
+    }
+
+    /// Closes the `n` most recently opened scopes, emitting `(pop n)` and permanently discarding
+    /// the variable declarations and asserts that were introduced inside them, so they can never
+    /// resurface in a later `generate_asserts` now that the solver itself has forgotten them.
+    /// Errors out without writing anything if fewer than `n` scopes are open.
+    pub fn pop<S: SMTProc>(&mut self, smt_proc: &mut S, n: usize) -> SMTResult<()> {
+        if n > self.scopes.len() {
+            return Err(SMTError::AssertionError(format!("cannot pop {} scopes, only {} open", n, self.scopes.len())));
+        }
+
+        let _ = smt_proc.write(format!("(pop {})\n", n));
+        for _ in 0..n {
+            let scope = self.scopes.pop().expect("scopes.len() checked above");
+            for name in scope.vars {
+                self.emitted_vars.remove(&name);
+                self.dead_vars.insert(name);
+            }
+            for idx in scope.asserts {
+                self.emitted_asserts.remove(&idx);
+                self.dead_asserts.insert(idx);
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks satisfiability under a set of boolean literal assumptions without permanently
+    /// asserting them, via `(check-sat-assuming (...))`. Still flushes any asserts generated so
+    /// far first, same as `check_sat`.
+    pub fn check_sat_assuming<S: SMTProc>(&mut self, smt_proc: &mut S, assumptions: &[NodeIndex]) -> SMTResult<bool> {
+        let _ = smt_proc.write(self.generate_asserts());
+
+        let mut literals = String::new();
+        for ni in assumptions {
+            literals = format!("{} {}", literals, self.expand_assertion(*ni));
+        }
+        let _ = smt_proc.write(format!("(check-sat-assuming ({}))\n", literals));
+
+        let read_result = smt_proc.read(None);
+
+        if read_result.is_ok() {
+            let read_string = read_result.unwrap();
+
+            if read_string == "sat\n" {
+                Ok(true)
+            } else {
+                Ok(false)
+            }
+        } else {
+            Err(SMTError::Undefined)
+        }
+    }
+
     // Recursive function that builds up the assertion string from the tree.
     pub fn expand_assertion(&self, ni: NodeIndex) -> String {
         let mut children = self.gr
@@ -133,29 +375,123 @@ impl<L: Logic> SMTLib2<L> {
         }
     }
 
+    /// Renders the constraint graph as Graphviz `dot` source.
+    pub fn to_dot(&self) -> String {
+        let mut result = "digraph constraints {\n".to_owned();
+
+        for ni in self.gr.node_indices() {
+            let node = &self.gr[ni];
+            let shape = if node.is_var() {
+                "ellipse"
+            } else if node.is_const() {
+                "box"
+            } else {
+                "diamond"
+            };
+            result = format!("{}    {} [label=\"{}\", shape={}];\n",
+                              result,
+                              ni.index(),
+                              node.to_string().replace("\"", "\\\""),
+                              shape);
+        }
+
+        for ni in self.gr.node_indices() {
+            let mut children = self.gr
+                                   .edges_directed(ni, EdgeDirection::Outgoing)
+                                   .map(|edge| {
+                                       match *edge.weight() {
+                                           EdgeData::EdgeOrder(ref i) => (edge.target(), *i),
+                                       }
+                                   })
+                                   .collect::<Vec<_>>();
+            children.sort_by(|x, y| (x.1).cmp(&y.1));
+
+            for (target, order) in children {
+                result = format!("{}    {} -> {} [label=\"{}\"];\n",
+                                  result,
+                                  ni.index(),
+                                  target.index(),
+                                  order);
+            }
+        }
+
+        result = format!("{}}}\n", result);
+        result
+    }
+
+    // Recovers the sort of an operand for assert-time checking: variables look their declared
+    // sort up in `var_map`, consts and fns fall back to `SMTNode::sort()`.
+    fn operand_sort(&self, ni: NodeIndex) -> Option<String> {
+        if let Some(name) = self.idx_map.get(&ni) {
+            if let Some(&(_, ref sort)) = self.var_map.get(name) {
+                return Some(sort.to_string());
+            }
+        }
+        self.gr[ni].sort()
+    }
+
     pub fn new_const<T: Into<L::Fns>>(&mut self, cval: T) -> NodeIndex {
         self.gr.add_node(cval.into())
     }
 
-    pub fn generate_asserts(&self) -> String {
-        // Write out all variable definitions.
+    // Only emits the delta since the last call: declarations and asserts that have already been
+    // written out to the solver (tracked in `emitted_vars` / `emitted_asserts`) are skipped, and
+    // ones that belonged to a scope popped off the assertion stack (tracked permanently in
+    // `dead_vars` / `dead_asserts`) are never re-emitted, so a warm solver process can be driven
+    // through many incremental `check_sat`/`push`/`pop` calls without re-sending the whole graph,
+    // or resurrecting something the solver has already forgotten.
+    pub fn generate_asserts(&mut self) -> String {
+        // Write out all variable definitions that haven't been sent yet.
         let mut decls = Vec::new();
+        let mut new_vars = Vec::new();
         for (name, val) in &self.var_map {
+            if self.emitted_vars.contains(name) || self.dead_vars.contains(name) {
+                continue;
+            }
             let ni = &val.0;
             let ty = &val.1;
             if self.gr[*ni].is_var() {
                 decls.push(format!("(declare-fun {} () {})\n", name, ty));
             }
+            new_vars.push(name.clone());
         }
-        // Identify root nodes and generate the assertion strings.
+        // Identify root nodes not yet asserted and generate the assertion strings.
         let mut assertions = Vec::new();
+        let mut new_asserts = Vec::new();
         for idx in self.gr.node_indices() {
+            if self.emitted_asserts.contains(&idx) || self.dead_asserts.contains(&idx) {
+                continue;
+            }
             if self.gr.edges_directed(idx, EdgeDirection::Incoming).collect::<Vec<_>>().is_empty() {
                 if self.gr[idx].is_fn() && self.gr[idx].is_bool() {
-                    assertions.push(format!("(assert {})\n", self.expand_assertion(idx)));
+                    let term = self.expand_assertion(idx);
+                    let term = if self.produce_unsat_cores {
+                        match self.assert_labels.get(&idx) {
+                            Some(label) => format!("(! {} :named {})", term, label),
+                            None => term,
+                        }
+                    } else {
+                        term
+                    };
+                    assertions.push(format!("(assert {})\n", term));
+                    new_asserts.push(idx);
                 }
             }
         }
+
+        for name in &new_vars {
+            self.emitted_vars.insert(name.clone());
+            if let Some(scope) = self.scopes.last_mut() {
+                scope.vars.push(name.clone());
+            }
+        }
+        for idx in &new_asserts {
+            self.emitted_asserts.insert(*idx);
+            if let Some(scope) = self.scopes.last_mut() {
+                scope.asserts.push(*idx);
+            }
+        }
+
         let mut result = String::new();
         for w in decls.iter().chain(assertions.iter()) {
             result = format!("{}{}", result, w)
@@ -182,27 +518,72 @@ impl<L: Logic> SMTLib2<L> {
         }
     }
 
-    fn parse_solver_output(&mut self, output: String) -> HashMap<NodeIndex, u64> {
-        let mut result: HashMap<NodeIndex, u64> = HashMap::new();
-        let re = Regex::new(r"\s+\(define-fun (?P<var>[0-9a-zA-Z_]+) \(\) [(]?[ _a-zA-Z0-9]+[)]?\n\s+(?P<val>([0-9]+|#x[0-9a-f]+|#b[01]+))")
+    // Parses a literal as it appears after a `define-fun` in the solver's `(get-model)` output,
+    // dispatching on its lexical form: `#x`/`#b` prefixed literals become `BitVec` (keeping every
+    // bit rather than folding into a `u64`), `true`/`false` become `Bool`, a leading `-` or a
+    // `(- n)` wrapper becomes a signed `Int`, a decimal with a `.` becomes `Real`, any other
+    // decimal becomes a plain `Int`, and text that parses as none of these is kept as `Bytes`.
+    fn parse_model_literal(val_str: &str) -> ModelValue {
+        if val_str == "true" || val_str == "false" {
+            return ModelValue::Bool(val_str == "true");
+        }
+
+        if val_str.len() > 2 && &val_str[0..2] == "#x" {
+            let digits = &val_str[2..];
+            let width = digits.len() * 4;
+            let bits = digits.chars()
+                              .rev()
+                              .flat_map(|c| {
+                                  let n = c.to_digit(16).unwrap();
+                                  (0..4).map(move |i| (n >> i) & 1 == 1)
+                              })
+                              .collect::<Vec<_>>();
+            return ModelValue::BitVec { bits: bits, width: width };
+        }
+
+        if val_str.len() > 2 && &val_str[0..2] == "#b" {
+            let digits = &val_str[2..];
+            let width = digits.len();
+            let bits = digits.chars().rev().map(|c| c == '1').collect::<Vec<_>>();
+            return ModelValue::BitVec { bits: bits, width: width };
+        }
+
+        let negated = val_str.starts_with("(- ") && val_str.ends_with(')');
+        let inner = if negated {
+            val_str[3..val_str.len() - 1].trim()
+        } else {
+            val_str
+        };
+
+        if inner.contains('.') {
+            if let Ok(f) = inner.parse::<f64>() {
+                return ModelValue::Real(if negated { -f } else { f });
+            }
+        }
+
+        if let Ok(i) = inner.parse::<BigInt>() {
+            return ModelValue::Int(if negated { -i } else { i });
+        }
+
+        ModelValue::Bytes(val_str.to_owned())
+    }
+
+    fn parse_solver_output(&mut self, output: String) -> HashMap<NodeIndex, ModelValue> {
+        let mut result: HashMap<NodeIndex, ModelValue> = HashMap::new();
+        let re = Regex::new(concat!(r"\s+\(define-fun (?P<var>[0-9a-zA-Z_]+) \(\) [(]?[ _a-zA-Z0-9]+[)]?\n\s+",
+                                     r"(?P<val>true|false|#x[0-9a-f]+|#b[01]+|\(- [0-9]+(?:\.[0-9]+)?\)|-?[0-9]+(?:\.[0-9]+)?)"))
                      .unwrap();
         for caps in re.captures_iter(&output) {
             let val_str = caps.name("val").unwrap();
-            let val = if val_str.len() > 2 && &val_str[0..2] == "#x" {
-                          u64::from_str_radix(&val_str[2..], 16)
-                      } else if val_str.len() > 2 && &val_str[0..2] == "#b" {
-                          u64::from_str_radix(&val_str[2..], 2)
-                      } else {
-                          val_str.parse::<u64>()
-                      }
-                      .unwrap();
             let vname = caps.name("var").unwrap();
-            result.insert(self.var_map[vname].0.clone(), val);
+            result.insert(self.var_map[vname].0.clone(), Self::parse_model_literal(val_str));
         }
         return result;
     }
 
-    pub fn solve_with_timeout<S: SMTProc>(&mut self, smt_proc: &mut S, timeout: u64) -> SMTResult<HashMap<NodeIndex, u64>> {
+    /// Like [`solve_with_timeout`](#method.solve_with_timeout), but returns the fully-typed
+    /// `ModelValue` for each variable instead of truncating everything to a `u64`.
+    pub fn solve_typed_with_timeout<S: SMTProc>(&mut self, smt_proc: &mut S, timeout: u64) -> SMTResult<HashMap<NodeIndex, ModelValue>> {
         let sat_result = self.check_sat(smt_proc);
 
         if !sat_result.is_ok() {
@@ -212,8 +593,7 @@ impl<L: Logic> SMTLib2<L> {
         }
 
         let _ = smt_proc.write("(get-model)\n".to_owned());
-        
-        let _ = smt_proc.read(Some(timeout));
+
         let read_result = smt_proc.read(Some(timeout));
 
         if read_result.is_ok() {
@@ -223,6 +603,11 @@ impl<L: Logic> SMTLib2<L> {
             Err(SMTError::Timeout)
         }
     }
+
+    pub fn solve_with_timeout<S: SMTProc>(&mut self, smt_proc: &mut S, timeout: u64) -> SMTResult<HashMap<NodeIndex, u64>> {
+        self.solve_typed_with_timeout(smt_proc, timeout)
+            .map(|model| model.iter().map(|(k, v)| (k.clone(), v.to_u64())).collect())
+    }
 }
 
 impl<L: Logic> SMTBackend for SMTLib2<L> {
@@ -252,13 +637,32 @@ impl<L: Logic> SMTBackend for SMTLib2<L> {
         let _ = smt_proc.write(format!("(set-logic {})\n", logic));
     }
 
-    fn assert<T: Into<L::Fns>>(&mut self, assert: T, ops: &[Self::Idx]) -> Self::Idx {
-        // TODO: Check correctness like operator arity.
-        let assertion = self.gr.add_node(assert.into());
+    fn assert<T: Into<L::Fns>>(&mut self, assert: T, ops: &[Self::Idx]) -> SMTResult<Self::Idx> {
+        let node = assert.into();
+
+        if let Some(expected) = node.signature() {
+            if expected.len() != ops.len() {
+                return Err(SMTError::ArityMismatch { expected: expected.len(), found: ops.len() });
+            }
+
+            for (position, (op, expected_sort)) in ops.iter().zip(expected.iter()).enumerate() {
+                if let Some(found_sort) = self.operand_sort(*op) {
+                    if &found_sort != expected_sort {
+                        return Err(SMTError::SortMismatch {
+                            expected: expected_sort.clone(),
+                            found: found_sort,
+                            position: position,
+                        });
+                    }
+                }
+            }
+        }
+
+        let assertion = self.gr.add_node(node);
         for (i, op) in ops.iter().enumerate() {
             self.gr.add_edge(assertion, *op, EdgeData::EdgeOrder(i));
         }
-        assertion
+        Ok(assertion)
     }
 
 
@@ -281,10 +685,18 @@ impl<L: Logic> SMTBackend for SMTLib2<L> {
         }
     }
 
-    // TODO: Return type information along with the value.
+    // Kept as a thin wrapper around `solve_typed` for callers that only deal in plain integers.
     fn solve<S: SMTProc>(&mut self, smt_proc: &mut S) -> SMTResult<HashMap<Self::Idx, u64>> {
-        let mut result = HashMap::new();
+        self.solve_typed(smt_proc)
+            .map(|model| model.iter().map(|(k, v)| (k.clone(), v.to_u64())).collect())
+    }
+}
 
+impl<L: Logic> SMTLib2<L> {
+    /// Like [`solve`](trait.SMTBackend.html#tymethod.solve), but returns the fully-typed
+    /// `ModelValue` for each variable (`Bool`, `BitVec`, `Int`, `Real` or `Bytes`) rather than
+    /// truncating everything into a `u64`.
+    pub fn solve_typed<S: SMTProc>(&mut self, smt_proc: &mut S) -> SMTResult<HashMap<NodeIndex, ModelValue>> {
         let sat_result = self.check_sat(smt_proc);
 
         if !sat_result.is_ok() {
@@ -294,11 +706,6 @@ impl<L: Logic> SMTBackend for SMTLib2<L> {
         }
 
         let _ = smt_proc.write("(get-model)\n".to_owned());
-        // XXX: For some reason we need two reads here in order to get the result from
-        // the SMT solver. Need to look into the reason for this. This might stop
-        // working in the
-        // future.
-        let _ = smt_proc.read(None);
         let read_result = smt_proc.read(None);
 
         if read_result.is_ok() {
@@ -310,24 +717,7 @@ impl<L: Logic> SMTBackend for SMTLib2<L> {
             //  (define-fun x () Int
             //    10)
             // )
-            let re = Regex::new(r"\s+\(define-fun (?P<var>[0-9a-zA-Z_]+) \(\) [(]?[ _a-zA-Z0-9]+[)]?\n\s+(?P<val>([0-9]+|#x[0-9a-f]+|#b[01]+))")
-                         .unwrap();
-            for caps in re.captures_iter(&read_string) {
-                // Here the caps.name("val") can be a hex value, or a binary value or a decimal
-                // value. We need to parse the output to a u64 accordingly.
-                let val_str = caps.name("val").unwrap();
-                let val = if val_str.len() > 2 && &val_str[0..2] == "#x" {
-                              u64::from_str_radix(&val_str[2..], 16)
-                          } else if val_str.len() > 2 && &val_str[0..2] == "#b" {
-                              u64::from_str_radix(&val_str[2..], 2)
-                          } else {
-                              val_str.parse::<u64>()
-                          }
-                          .unwrap();
-                let vname = caps.name("var").unwrap();
-                result.insert(self.var_map[vname].0.clone(), val);
-            }
-            Ok(result)
+            Ok(self.parse_solver_output(read_string))
         } else {
             Err(SMTError::Undefined)
         }